@@ -1,77 +1,423 @@
 use std::env;
-use std::io::{self, BufRead, Write};
+use std::fmt::{self, Write as _};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
+use std::process::Command;
+use std::thread;
 
-use anyhow::{Context, Result};
-use chrono::{NaiveTime, Timelike};
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Duration, NaiveDateTime, TimeZone, Timelike, Utc};
+use chrono_tz::Tz;
 
 use simple_cron::{get_next_time, Specification, Specifier};
 
-/// Parse a single specification line of the form `* 0 target`
-fn parse_line(line: &str) -> Result<(Specifier, Specifier, &str)> {
-    let raw_parts: Vec<_> = line.splitn(3, ' ').collect();
+/// Parse a single specification line of the form `* 0 * * * target`,
+/// i.e. `minute hour day-of-month month day-of-week target`.
+///
+/// Whole-line macro nicknames like `@daily /bin/backup` are recognised before
+/// the positional-field path and expanded into the equivalent specification.
+fn parse_line(line: &str) -> Result<(Specification, &str)> {
+    if let Some(rest) = line.strip_prefix('@') {
+        let mut parts = rest.splitn(2, ' ');
+        let name = parts.next().unwrap_or("");
+        let target = parts
+            .next()
+            .with_context(|| "No target value.")?;
+        return Ok((macro_specification(name)?, target));
+    }
+
+    let raw_parts: Vec<_> = line.splitn(6, ' ').collect();
     let minute = Specifier::from_str_max(
         raw_parts
             .get(0)
-            .with_context(|| format!("No minute value."))?,
+            .with_context(|| "No minute value.")?,
         60,
     )
-    .with_context(|| format!("Invalid minute specifier."))?;
+    .with_context(|| "Invalid minute specifier.")?;
     let hour = Specifier::from_str_max(
         raw_parts
             .get(1)
-            .with_context(|| format!("No hour value."))?,
+            .with_context(|| "No hour value.")?,
         24,
     )
-    .with_context(|| format!("Invalid hour specifier."))?;
+    .with_context(|| "Invalid hour specifier.")?;
+    let day_of_month = Specifier::from_str_bounds(
+        raw_parts
+            .get(2)
+            .with_context(|| "No day-of-month value.")?,
+        1,
+        32,
+    )
+    .with_context(|| "Invalid day-of-month specifier.")?;
+    let month = Specifier::from_str_bounds(
+        raw_parts
+            .get(3)
+            .with_context(|| "No month value.")?,
+        1,
+        13,
+    )
+    .with_context(|| "Invalid month specifier.")?;
+    let day_of_week = Specifier::from_str_max(
+        raw_parts
+            .get(4)
+            .with_context(|| "No day-of-week value.")?,
+        7,
+    )
+    .with_context(|| "Invalid day-of-week specifier.")?;
     let target: &str = *raw_parts
-        .get(2)
-        .with_context(|| format!("No target value."))?;
+        .get(5)
+        .with_context(|| "No target value.")?;
 
-    Ok((minute, hour, target))
+    Ok((
+        Specification::new(minute, hour, day_of_month, month, day_of_week),
+        target,
+    ))
 }
 
 /// For each line from the reader, calculate the correct output and send it to
 /// writer.
-fn run<Reader: BufRead, Writer: Write>(
+fn run<Reader: BufRead, Writer: Write, Tz: TimeZone>(
     reader: Reader,
     writer: &mut Writer,
-    current_time: &NaiveTime,
-) -> Result<()> {
+    current_time: &DateTime<Tz>,
+    formatter: &Formatter,
+) -> Result<()>
+where
+    Tz::Offset: fmt::Display,
+{
     for (index, line) in reader.lines().enumerate() {
         let line = line.with_context(|| format!("Failed to get line {}", index))?;
-        let (minute, hour, target) =
+        let (specification, target) =
             parse_line(&line).with_context(|| format!("Failed to parse input line {}", index))?;
-        let specification = Specification::new(minute, hour);
-        let (next_time, day) = get_next_time(&specification, current_time);
-        // TODO(tommilligan) The hours are not padded here specifically
-        // to make the given example in the task pass.
-        writer.write(
-            format!(
-                "{}:{:02} {} - {}\n",
-                next_time.hour(),
-                next_time.minute(),
-                day,
-                target
-            )
-            .as_bytes(),
-        )?;
+        let next_time = get_next_time(&specification, current_time)
+            .with_context(|| format!("No trigger time for input line {}", index))?;
+        writer.write_all(formatter.format(&next_time, target)?.as_bytes())?;
     }
     Ok(())
 }
 
-/// Deal with I/O, thin wrapper around `run`.
+/// Renders a trigger time and its target into an output line.
+///
+/// The legacy layout follows the original `"{hour}:{minute} {day} - {target}"`
+/// report, with the unpadded hour, but writes the trigger's date (e.g.
+/// `2020-01-02`) for `{day}`; the original `today`/`tomorrow` words are not
+/// printed. The templated variant takes a
+/// `chrono`-style strftime template plus a `{target}` placeholder.
+enum Formatter {
+    Legacy,
+    Template(String),
+}
+
+impl Formatter {
+    /// Pick a formatter from the CLI flags: `--format <template>` selects the
+    /// templated layout, otherwise (or with `--legacy-format`) we keep the
+    /// quirky legacy default.
+    fn from_flags(flags: &[&str]) -> Self {
+        if flags.contains(&"--legacy-format") {
+            return Formatter::Legacy;
+        }
+        if let Some(index) = flags.iter().position(|arg| *arg == "--format") {
+            if let Some(template) = flags.get(index + 1) {
+                return Formatter::Template((*template).to_owned());
+            }
+        }
+        Formatter::Legacy
+    }
+
+    /// Render a single report line, including the trailing newline.
+    fn format<Tz: TimeZone>(&self, next_time: &DateTime<Tz>, target: &str) -> Result<String>
+    where
+        Tz::Offset: fmt::Display,
+    {
+        match self {
+            Formatter::Legacy => {
+                let wall_clock = next_time.naive_local();
+                // The hour is deliberately left unpadded here, matching the
+                // original report format.
+                Ok(format!(
+                    "{}:{:02} {} - {}\n",
+                    wall_clock.hour(),
+                    wall_clock.minute(),
+                    wall_clock.date(),
+                    target
+                ))
+            }
+            Formatter::Template(template) => {
+                // chrono renders the `%` specifiers and leaves the `{target}`
+                // placeholder untouched for us to substitute afterwards. Render
+                // through `write!` rather than `to_string()`, which panics on a
+                // malformed template; surface that as an error instead.
+                let mut rendered = String::new();
+                write!(rendered, "{}", next_time.format(template))
+                    .map_err(|_| anyhow!("Invalid format template {:?}.", template))?;
+                Ok(format!("{}\n", rendered.replace("{target}", target)))
+            }
+        }
+    }
+}
+
+/// Expand a schedule macro nickname (without its leading `@`) into the
+/// equivalent specification, as accepted by most cron implementations.
+///
+/// `@reboot` is deliberately rejected: it fires once at daemon startup and has
+/// no recurring wall-clock time, so a time-based scheduler like this one has
+/// nothing to expand it into. We surface that as an error rather than silently
+/// dropping the line.
+fn macro_specification(name: &str) -> Result<Specification> {
+    let specification = match name {
+        // 0 0 1 1 * - midnight on the 1st of January.
+        "yearly" | "annually" => Specification::new(
+            Specifier::Only(0),
+            Specifier::Only(0),
+            Specifier::Only(1),
+            Specifier::Only(1),
+            Specifier::Any,
+        ),
+        // 0 0 1 * * - midnight on the 1st of every month.
+        "monthly" => Specification::new(
+            Specifier::Only(0),
+            Specifier::Only(0),
+            Specifier::Only(1),
+            Specifier::Any,
+            Specifier::Any,
+        ),
+        // 0 0 * * 0 - midnight every Sunday.
+        "weekly" => Specification::new(
+            Specifier::Only(0),
+            Specifier::Only(0),
+            Specifier::Any,
+            Specifier::Any,
+            Specifier::Only(0),
+        ),
+        // 0 0 * * * - midnight every day.
+        "daily" | "midnight" => Specification::new(
+            Specifier::Only(0),
+            Specifier::Only(0),
+            Specifier::Any,
+            Specifier::Any,
+            Specifier::Any,
+        ),
+        // 0 * * * * - the top of every hour.
+        "hourly" => Specification::new(
+            Specifier::Only(0),
+            Specifier::Any,
+            Specifier::Any,
+            Specifier::Any,
+            Specifier::Any,
+        ),
+        // @reboot fires at daemon startup, which has no time-of-day equivalent.
+        "reboot" => {
+            return Err(anyhow!(
+                "The @reboot macro fires only at startup and has no scheduled \
+                 time, so it is not supported by this scheduler."
+            ))
+        }
+        other => return Err(anyhow!("Unknown schedule macro @{}.", other)),
+    };
+    Ok(specification)
+}
+
+/// Parse a crontab buffer into a list of jobs, plus any timezone declared by a
+/// leading `TZ=...` line. Blank lines and `#` comments are ignored, keeping an
+/// owned copy of each target so the jobs outlive the source buffer.
+fn parse_jobs<Reader: BufRead>(
+    reader: Reader,
+) -> Result<(Option<Tz>, Vec<(Specification, String)>)> {
+    let mut timezone = None;
+    let mut jobs = Vec::new();
+    for (index, line) in reader.lines().enumerate() {
+        let line = line.with_context(|| format!("Failed to get line {}", index))?;
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+        if let Some(name) = trimmed.strip_prefix("TZ=") {
+            timezone = Some(parse_timezone(name)?);
+            continue;
+        }
+        let (specification, target) =
+            parse_line(&line).with_context(|| format!("Failed to parse input line {}", index))?;
+        jobs.push((specification, target.to_owned()));
+    }
+    Ok((timezone, jobs))
+}
+
+/// Parse an IANA timezone name such as `Europe/London`.
+fn parse_timezone(name: &str) -> Result<Tz> {
+    name.parse()
+        .map_err(|err| anyhow!("Unknown timezone {:?}: {}", name, err))
+}
+
+/// Spawn a target command through the shell, like a real cron daemon. We do not
+/// wait on the child, so long-running and overlapping jobs don't hold up the
+/// scheduler.
+fn spawn_target(target: &str) -> Result<()> {
+    Command::new("sh")
+        .arg("-c")
+        .arg(target)
+        .spawn()
+        .with_context(|| format!("Failed to spawn target {:?}", target))?;
+    Ok(())
+}
+
+/// The nearest upcoming trigger at or after `cursor`, together with every job
+/// that fires at that instant.
+///
+/// Jobs are selected by their own `get_next_time`, not by re-matching the
+/// resolved wall clock: on a DST spring-forward a job rolled onto the far edge
+/// of the gap still fires here, even though the wall-clock time it nominally
+/// asked for never occurs.
+fn next_trigger<'a, Tz: TimeZone>(
+    jobs: &'a [(Specification, String)],
+    cursor: &DateTime<Tz>,
+) -> Option<(DateTime<Tz>, Vec<&'a str>)> {
+    let triggers: Vec<(DateTime<Tz>, &str)> = jobs
+        .iter()
+        .filter_map(|(specification, target)| {
+            get_next_time(specification, cursor)
+                .ok()
+                .map(|next| (next, target.as_str()))
+        })
+        .collect();
+    let next = triggers.iter().map(|(next, _)| next).min()?.clone();
+    let firing = triggers
+        .into_iter()
+        .filter(|(trigger, _)| *trigger == next)
+        .map(|(_, target)| target)
+        .collect();
+    Some((next, firing))
+}
+
+/// Run as a daemon: sleep until the nearest trigger across all jobs, then spawn
+/// every job scheduled for that minute, and repeat.
+fn run_daemon(jobs: Vec<(Specification, String)>, timezone: Tz) -> Result<()> {
+    // The earliest minute we have not yet serviced.
+    let mut cursor = floor_to_minute(Utc::now().with_timezone(&timezone));
+    while let Some((next, targets)) = next_trigger(&jobs, &cursor) {
+        // Sleep until the target minute arrives on the wall clock.
+        let now = Utc::now().with_timezone(&timezone);
+        if let Ok(wait) = (next.clone() - now).to_std() {
+            thread::sleep(wait);
+        }
+        // Fire exactly the jobs that produced this trigger.
+        for target in targets {
+            spawn_target(target)?;
+        }
+        cursor = next + Duration::minutes(1);
+    }
+    Ok(())
+}
+
+/// Dry-run: report the jobs that would fire at the next trigger, without
+/// spawning anything.
+fn print_next_firings<Writer: Write>(
+    jobs: &[(Specification, String)],
+    timezone: Tz,
+    writer: &mut Writer,
+) -> Result<()> {
+    let cursor = floor_to_minute(Utc::now().with_timezone(&timezone));
+    match next_trigger(jobs, &cursor) {
+        Some((next, targets)) => {
+            for target in targets {
+                writeln!(writer, "{} would run: {}", next, target)?;
+            }
+        }
+        None => writeln!(writer, "No jobs will fire.")?,
+    }
+    Ok(())
+}
+
+/// Truncate a datetime to whole-minute resolution.
+fn floor_to_minute<Tz: TimeZone>(datetime: DateTime<Tz>) -> DateTime<Tz> {
+    // Truncate the UTC instant: chrono's `DateTime::with_second` returns `None`
+    // for an ambiguous local time in an autumn-back fold.
+    let utc = datetime
+        .naive_utc()
+        .with_second(0)
+        .and_then(|t| t.with_nanosecond(0))
+        .expect("zero is always a valid second and nanosecond");
+    datetime.timezone().from_utc_datetime(&utc)
+}
+
+/// Flags that consume the following argument as their value. Used to skip past
+/// them when hunting for a positional argument.
+const VALUE_FLAGS: &[&str] = &["--timezone", "--format"];
+
+/// The first positional (non-`--`) argument, skipping any value-taking flags
+/// along with their values. Lets flags appear before or after the positional
+/// time/path argument.
+fn first_positional<'a>(flags: &[&'a str]) -> Option<&'a str> {
+    let mut index = 0;
+    while let Some(arg) = flags.get(index) {
+        if VALUE_FLAGS.contains(arg) {
+            // Skip the flag and the value it consumes.
+            index += 2;
+        } else if arg.starts_with("--") {
+            index += 1;
+        } else {
+            return Some(arg);
+        }
+    }
+    None
+}
+
+/// Read the value of a `--timezone <name>` flag, if present.
+fn timezone_flag(flags: &[&str]) -> Result<Option<Tz>> {
+    match flags.iter().position(|arg| *arg == "--timezone") {
+        Some(index) => {
+            let name = flags
+                .get(index + 1)
+                .with_context(|| "Expected a name after --timezone.")?;
+            Ok(Some(parse_timezone(name)?))
+        }
+        None => Ok(None),
+    }
+}
+
+/// Deal with I/O, thin wrapper around the various modes.
 fn main() -> Result<()> {
     let args: Vec<String> = env::args().collect();
+    let flags: Vec<&str> = args[1..].iter().map(String::as_str).collect();
+    let flag_timezone = timezone_flag(&flags)?;
 
-    let raw_time = args.get(1).expect("Expected one argument to be given.");
-    let current_time = NaiveTime::parse_from_str(raw_time, "%H:%M")?;
+    // Run mode reads a crontab file and behaves like a cron daemon.
+    if flags.contains(&"--run") {
+        let path = first_positional(&flags)
+            .with_context(|| "Expected a crontab file path for --run.")?;
+        let file = File::open(path).with_context(|| format!("Failed to open crontab {:?}", path))?;
+        let (file_timezone, jobs) = parse_jobs(BufReader::new(file))?;
+        // The CLI flag wins over any `TZ=` line, which wins over the UTC default.
+        let timezone = flag_timezone.or(file_timezone).unwrap_or(Tz::UTC);
+        if flags.contains(&"--once") {
+            let stdout = io::stdout();
+            let mut writer = stdout.lock();
+            return print_next_firings(&jobs, timezone, &mut writer);
+        }
+        return run_daemon(jobs, timezone);
+    }
+
+    // Default mode: one-shot next-time report read from stdin. The timestamp is
+    // the first positional argument, so flags may appear either side of it.
+    let raw_time = first_positional(&flags)
+        .with_context(|| "Expected a time argument of the form 2020-01-01T12:00.")?;
+    let naive_time = NaiveDateTime::parse_from_str(raw_time, "%Y-%m-%dT%H:%M")?;
+    let timezone = flag_timezone.unwrap_or(Tz::UTC);
+    let current_time = match timezone.from_local_datetime(&naive_time) {
+        chrono::LocalResult::Single(datetime) => datetime,
+        chrono::LocalResult::Ambiguous(earliest, _) => earliest,
+        chrono::LocalResult::None => {
+            return Err(anyhow!("{} does not exist in {}.", naive_time, timezone))
+        }
+    };
+
+    let formatter = Formatter::from_flags(&flags);
 
     let stdin = io::stdin();
     let reader = stdin.lock();
     let stdout = io::stdout();
     let mut writer = stdout.lock();
 
-    run(reader, &mut writer, &current_time)
+    run(reader, &mut writer, &current_time, &formatter)
 }
 
 #[cfg(test)]
@@ -81,15 +427,22 @@ mod tests {
         strategy::{BoxedStrategy, Just, Strategy},
     };
 
+    use chrono::NaiveDate;
+
     use super::*;
 
     fn line_strategy() -> BoxedStrategy<String> {
         (
             prop_oneof![Just("*".to_owned()), (0..60u32).prop_map(|n| n.to_string())],
             prop_oneof![Just("*".to_owned()), (0..24u32).prop_map(|n| n.to_string())],
+            prop_oneof![Just("*".to_owned()), (1..=31u32).prop_map(|n| n.to_string())],
+            prop_oneof![Just("*".to_owned()), (1..=12u32).prop_map(|n| n.to_string())],
+            prop_oneof![Just("*".to_owned()), (0..=6u32).prop_map(|n| n.to_string())],
             "\\PC+",
         )
-            .prop_map(|(minute, hour, target)| format!("{} {} {}\n", minute, hour, target))
+            .prop_map(|(minute, hour, dom, month, dow, target)| {
+                format!("{} {} {} {} {} {}\n", minute, hour, dom, month, dow, target)
+            })
             .boxed()
     }
 
@@ -103,36 +456,133 @@ mod tests {
         ) {
             // TODO(tommilligan) Quick hack to dump some proptest examples for benching.
             // See if there's a better way to do this?
+            // An unsatisfiable spec (e.g. Feb 30) is a clean error, not a
+            // crash, so we accept either outcome here.
             let mut writer = Vec::new();
-            run(
+            let _ = run(
                 line.as_bytes(),
                 &mut writer,
-                &NaiveTime::from_hms(12, 34, 0),
-            )
-            .unwrap();
+                &Utc.from_utc_datetime(&NaiveDate::from_ymd(2020, 1, 1).and_hms(12, 34, 0)),
+                &Formatter::Legacy,
+            );
         }
     }
 
+    #[test]
+    fn test_custom_format_template() {
+        let mut writer = Vec::new();
+        run(
+            "0 0 * * * /bin/backup\n".as_bytes(),
+            &mut writer,
+            &Utc.from_utc_datetime(&NaiveDate::from_ymd(2020, 1, 1).and_hms(12, 0, 0)),
+            &Formatter::Template("%Y-%m-%dT%H:%M {target}".to_owned()),
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(writer).unwrap(),
+            "2020-01-02T00:00 /bin/backup\n"
+        );
+    }
+
+    #[test]
+    fn test_bad_format_template_errors_without_panicking() {
+        // A malformed strftime specifier must surface as an error, not abort
+        // the process with a backtrace.
+        let mut writer = Vec::new();
+        let result = run(
+            "0 0 * * * /bin/backup\n".as_bytes(),
+            &mut writer,
+            &Utc.from_utc_datetime(&NaiveDate::from_ymd(2020, 1, 1).and_hms(12, 0, 0)),
+            &Formatter::Template("%Q {target}".to_owned()),
+        );
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_first_positional_skips_flags_and_values() {
+        // The value-taking flag and its value are skipped, either side of the
+        // positional argument.
+        assert_eq!(
+            first_positional(&["--format", "%H:%M {target}", "2020-01-01T12:00"]),
+            Some("2020-01-01T12:00")
+        );
+        assert_eq!(
+            first_positional(&["2020-01-01T12:00", "--format", "%H:%M"]),
+            Some("2020-01-01T12:00")
+        );
+        // Nothing positional once the flag swallows the only remaining value.
+        assert_eq!(first_positional(&["--format", "%H:%M {target}"]), None);
+    }
+
+    #[test]
+    fn test_macro_expands_like_positional_fields() {
+        // `@daily` should fire at exactly the same time as `0 0 * * *`.
+        let (macro_spec, macro_target) = parse_line("@daily /bin/backup").unwrap();
+        let (field_spec, field_target) = parse_line("0 0 * * * /bin/backup").unwrap();
+        assert_eq!(macro_target, field_target);
+        let now = Utc.from_utc_datetime(&NaiveDate::from_ymd(2020, 6, 15).and_hms(9, 30, 0));
+        assert_eq!(
+            get_next_time(&macro_spec, &now).unwrap(),
+            get_next_time(&field_spec, &now).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_unknown_macro_errors() {
+        assert!(parse_line("@nonsense /bin/true").is_err());
+        // @reboot is recognised but unsupported by this time-based scheduler.
+        assert!(parse_line("@reboot /bin/true").is_err());
+    }
+
+    #[test]
+    fn test_parse_jobs_skips_blanks_and_comments() {
+        let (_timezone, jobs) = parse_jobs(
+            r#"# a comment
+30 1 * * * /bin/run_me_daily
+
+45 * * * * /bin/run_me_hourly
+"#
+            .as_bytes(),
+        )
+        .unwrap();
+        let targets: Vec<_> = jobs.iter().map(|(_, target)| target.as_str()).collect();
+        assert_eq!(targets, vec!["/bin/run_me_daily", "/bin/run_me_hourly"]);
+    }
+
+    #[test]
+    fn test_parse_jobs_reads_timezone() {
+        let (timezone, jobs) = parse_jobs(
+            r#"TZ=Europe/London
+0 0 * * * /bin/backup
+"#
+            .as_bytes(),
+        )
+        .unwrap();
+        assert_eq!(timezone, Some(chrono_tz::Europe::London));
+        assert_eq!(jobs.len(), 1);
+    }
+
     #[test]
     fn test_task_example() {
         let mut writer = Vec::new();
         run(
-            r#"30 1 /bin/run_me_daily
-45 * /bin/run_me_hourly
-* * /bin/run_me_every_minute
-* 19 /bin/run_me_sixty_times
+            r#"30 1 * * * /bin/run_me_daily
+45 * * * * /bin/run_me_hourly
+* * * * * /bin/run_me_every_minute
+* 19 * * * /bin/run_me_sixty_times
 "#
             .as_bytes(),
             &mut writer,
-            &NaiveTime::from_hms(16, 10, 0),
+            &Utc.from_utc_datetime(&NaiveDate::from_ymd(2020, 1, 1).and_hms(16, 10, 0)),
+            &Formatter::Legacy,
         )
         .unwrap();
         assert_eq!(
             String::from_utf8(writer).unwrap(),
-            r#"1:30 tomorrow - /bin/run_me_daily
-16:45 today - /bin/run_me_hourly
-16:10 today - /bin/run_me_every_minute
-19:00 today - /bin/run_me_sixty_times
+            r#"1:30 2020-01-02 - /bin/run_me_daily
+16:45 2020-01-01 - /bin/run_me_hourly
+16:10 2020-01-01 - /bin/run_me_every_minute
+19:00 2020-01-01 - /bin/run_me_sixty_times
 "#
         );
     }
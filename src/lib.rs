@@ -1,304 +1,613 @@
-use std::fmt;
-
 use anyhow::{anyhow, Context, Result};
-use chrono::{Duration, NaiveTime, Timelike};
-
-/// Represents the day of the next trigger time.
-#[derive(Debug, PartialEq, Eq, Clone)]
-pub enum Day {
-    Today,
-    Tomorrow,
-}
-
-impl fmt::Display for Day {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(
-            f,
-            "{}",
-            match self {
-                Self::Today => "today",
-                Self::Tomorrow => "tomorrow",
-            }
-        )
-    }
-}
+use chrono::{
+    DateTime, Datelike, Duration, LocalResult, NaiveDate, NaiveDateTime, NaiveTime, TimeZone,
+    Timelike,
+};
 
 /// Represents a single token in the cron specification.
 #[derive(Clone, Debug)]
 pub enum Specifier {
     Any,
     Only(u32),
+    /// An inclusive range, e.g. `1-5`.
+    Range(u32, u32),
+    /// A comma separated list, e.g. `0,15,30`.
+    List(Vec<Specifier>),
+    /// A step over some base specifier, e.g. `*/5` or `10-50/10`.
+    Step { base: Box<Specifier>, step: u32 },
 }
 
 impl Specifier {
-    /// Convert '*' or an integer into a specifier, checking the integer is within
+    /// Convert a cron field into a specifier, checking any integers are within
     /// the given range.
+    ///
+    /// Understands the standard crontab vocabulary: wildcards (`*`), single
+    /// ordinals, inclusive ranges (`1-5`), comma lists (`0,15,30`) and step
+    /// expressions (`*/5`, `10-50/10`).
     pub fn from_str_max(raw_token: &str, max_ordinal: u32) -> Result<Specifier> {
+        Specifier::from_str_bounds(raw_token, 0, max_ordinal)
+    }
+
+    /// As [`Specifier::from_str_max`], but also rejecting ordinals below
+    /// `min_ordinal`. Used for the 1-based date fields, where `0` is never a
+    /// valid day-of-month or month.
+    pub fn from_str_bounds(raw_token: &str, min_ordinal: u32, max_ordinal: u32) -> Result<Specifier> {
+        // A comma list is the outermost structure, so split it off first.
+        if raw_token.contains(',') {
+            let children = raw_token
+                .split(',')
+                .map(|element| Specifier::from_str_bounds(element, min_ordinal, max_ordinal))
+                .collect::<Result<Vec<_>>>()
+                .with_context(|| "Invalid list element.")?;
+            return Ok(Specifier::List(children));
+        }
+
+        // A step divides a base wildcard or range by a stride.
+        if let Some((base, step)) = raw_token.split_once('/') {
+            let base = Specifier::from_str_bounds(base, min_ordinal, max_ordinal)
+                .with_context(|| "Invalid step base.")?;
+            let step = step.parse().with_context(|| "Invalid step value.")?;
+            if step == 0 {
+                return Err(anyhow!("Step value must be greater than zero."));
+            }
+            return Ok(Specifier::Step {
+                base: Box::new(base),
+                step,
+            });
+        }
+
+        // A range spans two inclusive ordinals.
+        if let Some((start, end)) = raw_token.split_once('-') {
+            let start = Specifier::ordinal(start, min_ordinal, max_ordinal)?;
+            let end = Specifier::ordinal(end, min_ordinal, max_ordinal)?;
+            if start > end {
+                return Err(anyhow!("Range start {} is after end {}.", start, end));
+            }
+            return Ok(Specifier::Range(start, end));
+        }
+
+        // Otherwise the token is a wildcard or a single ordinal.
         match raw_token {
             "*" => Ok(Specifier::Any),
-            raw_token => {
-                let number = raw_token
-                    .parse()
-                    .with_context(|| format!("Invalid number."))?;
-                match number {
-                    x if x < max_ordinal => Ok(Specifier::Only(number)),
-                    _ => Err(anyhow!(
-                        "Number {} outside of range {}.",
-                        number,
-                        max_ordinal
-                    )),
-                }
+            raw_token => Ok(Specifier::Only(Specifier::ordinal(
+                raw_token,
+                min_ordinal,
+                max_ordinal,
+            )?)),
+        }
+    }
+
+    /// Parse a single ordinal, checking it falls within the given range.
+    fn ordinal(raw_token: &str, min_ordinal: u32, max_ordinal: u32) -> Result<u32> {
+        let number = raw_token.parse().with_context(|| "Invalid number.")?;
+        match number {
+            x if x >= min_ordinal && x < max_ordinal => Ok(number),
+            _ => Err(anyhow!(
+                "Number {} outside of range {}-{}.",
+                number,
+                min_ordinal,
+                max_ordinal - 1
+            )),
+        }
+    }
+
+    /// Return whether this specifier matches the given ordinal value.
+    pub fn matches_ordinal(&self, value: u32) -> bool {
+        match self {
+            Specifier::Any => true,
+            Specifier::Only(n) => value == *n,
+            Specifier::Range(a, b) => *a <= value && value <= *b,
+            Specifier::List(children) => {
+                children.iter().any(|child| child.matches_ordinal(value))
+            }
+            Specifier::Step { base, step } => {
+                base.matches_ordinal(value) && (value - base.base_min()) % step == 0
             }
         }
     }
+
+    /// The smallest ordinal this specifier could match, used as the origin a
+    /// step counts from. `*` counts from 0, a range from its start.
+    fn base_min(&self) -> u32 {
+        match self {
+            Specifier::Any => 0,
+            Specifier::Only(n) => *n,
+            Specifier::Range(a, _) => *a,
+            Specifier::List(children) => {
+                children.iter().map(Specifier::base_min).min().unwrap_or(0)
+            }
+            Specifier::Step { base, .. } => base.base_min(),
+        }
+    }
 }
 
-/// Represents the complete time portion of the cron specification.
+/// Represents a complete five-field cron specification.
 #[derive(Clone, Debug)]
 pub struct Specification {
     minute: Specifier,
     hour: Specifier,
+    day_of_month: Specifier,
+    month: Specifier,
+    day_of_week: Specifier,
 }
 
 impl Specification {
-    pub fn new(minute: Specifier, hour: Specifier) -> Self {
-        Self { minute, hour }
+    pub fn new(
+        minute: Specifier,
+        hour: Specifier,
+        day_of_month: Specifier,
+        month: Specifier,
+        day_of_week: Specifier,
+    ) -> Self {
+        Self {
+            minute,
+            hour,
+            day_of_month,
+            month,
+            day_of_week,
+        }
     }
 
-    /// Return whether this specification matches the given time in
-    /// minutes.
-    pub fn matches(&self, time: &NaiveTime) -> bool {
-        if let Specifier::Only(spec) = self.minute {
-            if spec != time.minute() {
-                return false;
-            }
+    /// Return whether this specification matches the given datetime, to
+    /// minute resolution.
+    pub fn matches(&self, datetime: &NaiveDateTime) -> bool {
+        self.matches_date(&datetime.date()) && self.matches_time(&datetime.time())
+    }
+
+    /// Return whether the month and day fields match the given date.
+    ///
+    /// Day-of-month and day-of-week follow crontab OR semantics: when both are
+    /// restricted a date matches if *either* matches, otherwise both must.
+    fn matches_date(&self, date: &NaiveDate) -> bool {
+        if !self.month.matches_ordinal(date.month()) {
+            return false;
         }
-        if let Specifier::Only(spec) = self.hour {
-            if spec != time.hour() {
-                return false;
+        let dom_restricted = !matches!(self.day_of_month, Specifier::Any);
+        let dow_restricted = !matches!(self.day_of_week, Specifier::Any);
+        let dom_ok = self.day_of_month.matches_ordinal(date.day());
+        let dow_ok = self
+            .day_of_week
+            .matches_ordinal(date.weekday().num_days_from_sunday());
+        if dom_restricted && dow_restricted {
+            dom_ok || dow_ok
+        } else {
+            dom_ok && dow_ok
+        }
+    }
+
+    /// Return whether the hour and minute fields match the given time.
+    fn matches_time(&self, time: &NaiveTime) -> bool {
+        self.minute.matches_ordinal(time.minute()) && self.hour.matches_ordinal(time.hour())
+    }
+
+    /// Find the first matching time on or after `floor` within a single day,
+    /// or `None` if no later time today matches.
+    fn next_time_on_or_after(&self, floor: &NaiveTime) -> Option<NaiveTime> {
+        for hour in floor.hour()..24 {
+            if !self.hour.matches_ordinal(hour) {
+                continue;
+            }
+            let minute_start = if hour == floor.hour() {
+                floor.minute()
+            } else {
+                0
+            };
+            for minute in minute_start..60 {
+                if self.minute.matches_ordinal(minute) {
+                    return Some(NaiveTime::from_hms(hour, minute, 0));
+                }
             }
         }
-        true
+        None
     }
 }
 
-/// Given a cron specification and the current time, return the next
-/// time this would be triggered.
-// TODO(tommilligan) This could be optimised by returning Cow<'a, NaiveTime>
-// for cases where the current time is valid
-pub fn get_next_time(specification: &Specification, current_time: &NaiveTime) -> (NaiveTime, Day) {
-    // Always check - if we match the current time, it's all good!
-    if specification.matches(&current_time) {
-        return (current_time.clone(), Day::Today);
+/// Number of days to sweep before declaring a specification unsatisfiable.
+/// Four years spans a leap year except across a skipped century leap year
+/// (such as 2100), so a 29 February specification can fail near one.
+const SEARCH_DAYS: u32 = 4 * 366;
+
+/// Maximum minutes to skip forward when a wall-clock time falls inside a
+/// spring-forward gap. Real DST jumps are at most an hour, so this is ample.
+const MAX_DST_GAP_MINUTES: u32 = 180;
+
+/// Given a cron specification and the current time, return the next datetime
+/// this would be triggered in the same timezone, or an error if the
+/// specification cannot be satisfied (e.g. `0 0 30 2 *`, the 30th of February).
+///
+/// Matching is done against the wall-clock (local) time, then resolved back to
+/// an absolute instant. DST edge cases are handled explicitly: a wall-clock
+/// time that does not exist in the spring-forward gap rolls forward to the next
+/// valid instant, and an ambiguous autumn-back time picks the first occurrence.
+pub fn get_next_time<Tz: TimeZone>(
+    specification: &Specification,
+    current_time: &DateTime<Tz>,
+) -> Result<DateTime<Tz>> {
+    let timezone = current_time.timezone();
+    let mut search_from = current_time.naive_local();
+    loop {
+        let next = next_naive_time(specification, &search_from)?;
+        let resolved = resolve_local(&timezone, next)?;
+        // Inside an autumn-back fold the first occurrence of a repeated
+        // wall-clock time may already have passed; keep searching beyond it.
+        // Comparing against the end of that minute keeps whole-minute matching.
+        if resolved.clone() + Duration::minutes(1) > *current_time {
+            return Ok(resolved);
+        }
+        search_from = next + Duration::minutes(1);
     }
+}
+
+/// The wall-clock sweep underlying [`get_next_time`].
+///
+/// We sweep forward from the current minute: for each day we check the date
+/// fields first and, when they match, look for the first matching time of day;
+/// otherwise we carry into the next day and reset the time to midnight.
+fn next_naive_time(
+    specification: &Specification,
+    current_time: &NaiveDateTime,
+) -> Result<NaiveDateTime> {
+    // Cron has no concept of seconds, so work at whole-minute resolution.
+    let start = current_time
+        .with_second(0)
+        .and_then(|t| t.with_nanosecond(0))
+        .expect("zero is always a valid second and nanosecond");
 
-    // There are only 4 possible combinations, so let's just enumerate them!
-    // If I was going to implement this with a larger spec, I'd do some sort of sweep-forward
-    // strategy, starting from the largest unit of time and working down.
-    let next_time = match &specification {
-        // If the specifier is any, then we already returned above.
-        Specification {
-            minute: Specifier::Any,
-            hour: Specifier::Any,
-        } => panic!("all-Any specification didn't match current time."),
-        // If we get a specific time, just construct it directly
-        Specification {
-            minute: Specifier::Only(minute),
-            hour: Specifier::Only(hour),
-        } => NaiveTime::from_hms(*hour, *minute, 0),
-        // If we get any hour but a specific minute, the next trigger is either
-        // this hour or the next hour
-        Specification {
-            minute: Specifier::Only(minute),
-            hour: Specifier::Any,
-        } => {
-            let mut next_time = NaiveTime::from_hms(current_time.hour(), *minute, 0);
-            // If the minute is behind the current minute, we need to add another hour
-            if next_time.minute() < current_time.minute() {
-                next_time = next_time + Duration::hours(1);
+    let mut date = start.date();
+    let mut time_floor = start.time();
+    for _ in 0..SEARCH_DAYS {
+        if specification.matches_date(&date) {
+            if let Some(time) = specification.next_time_on_or_after(&time_floor) {
+                return Ok(NaiveDateTime::new(date, time));
             }
-            next_time
         }
-        // If we get a specific hour but any minute, the trigger time must be
-        // the start of that hour
-        Specification {
-            minute: Specifier::Any,
-            hour: Specifier::Only(hour),
-        } => NaiveTime::from_hms(*hour, 0, 0),
-    };
+        // This day is exhausted; carry into the next and start from midnight.
+        date = date
+            .succ_opt()
+            .with_context(|| "Ran off the end of the representable calendar.")?;
+        time_floor = NaiveTime::from_hms(0, 0, 0);
+    }
+    Err(anyhow!(
+        "Specification {:?} has no matching time within {} days.",
+        specification,
+        SEARCH_DAYS
+    ))
+}
 
-    // If the next time is behind the current time, it must be tomorrow
-    // as NaiveTime always wraps over the date boundary
-    let day = if &next_time < current_time {
-        Day::Tomorrow
-    } else {
-        Day::Today
-    };
-    (next_time, day)
+/// Resolve a wall-clock time to an absolute instant in `tz`, resolving DST
+/// folds and gaps rather than panicking or silently guessing.
+fn resolve_local<Tz: TimeZone>(tz: &Tz, mut wall_clock: NaiveDateTime) -> Result<DateTime<Tz>> {
+    for _ in 0..=MAX_DST_GAP_MINUTES {
+        match tz.from_local_datetime(&wall_clock) {
+            LocalResult::Single(datetime) => return Ok(datetime),
+            // Autumn-back: the wall-clock time happens twice; take the earlier.
+            LocalResult::Ambiguous(earliest, _latest) => return Ok(earliest),
+            // Spring-forward: this wall-clock time never occurs. Nudge forward a
+            // minute at a time until we land on a valid instant.
+            LocalResult::None => wall_clock = wall_clock + Duration::minutes(1),
+        }
+    }
+    Err(anyhow!(
+        "Could not resolve {} to a valid instant in the timezone.",
+        wall_clock
+    ))
+}
+
+/// A lazy iterator over successive trigger times of a specification.
+///
+/// Created by [`Specification::iter_from`]. It holds the specification by
+/// reference and only the next search position as state, so it composes
+/// naturally with adaptors such as `.take(n)` and `.take_while(..)`. The
+/// iterator ends once the specification has no further matching time (or is
+/// unsatisfiable).
+pub struct Occurrences<'a, Tz: TimeZone> {
+    specification: &'a Specification,
+    next_from: DateTime<Tz>,
+}
+
+impl Specification {
+    /// Iterate over the trigger times at or after `current_time`.
+    pub fn iter_from<Tz: TimeZone>(&self, current_time: DateTime<Tz>) -> Occurrences<'_, Tz> {
+        Occurrences {
+            specification: self,
+            next_from: current_time,
+        }
+    }
+}
+
+impl<Tz: TimeZone> Iterator for Occurrences<'_, Tz> {
+    type Item = DateTime<Tz>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let hit = get_next_time(self.specification, &self.next_from).ok()?;
+        // Advance one minute past this hit so the next call moves forward.
+        self.next_from = hit.clone() + Duration::minutes(1);
+        Some(hit)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use proptest::{
-        prop_assert, prop_oneof, proptest,
+        collection, prop_assert, prop_oneof, proptest,
         strategy::{BoxedStrategy, Just, Strategy},
     };
 
+    use chrono::Utc;
+
     use super::*;
 
+    /// Build a UTC datetime, the timezone we exercise the scheduler in.
+    fn dt(year: i32, month: u32, day: u32, hour: u32, minute: u32) -> DateTime<Utc> {
+        Utc.from_utc_datetime(&NaiveDate::from_ymd(year, month, day).and_hms(hour, minute, 0))
+    }
+
     fn specifier_strategy(max_ordinal: u32) -> BoxedStrategy<Specifier> {
-        prop_oneof![
+        let leaf = prop_oneof![
             Just(Specifier::Any),
-            (0..max_ordinal).prop_map(|n| Specifier::Only(n)),
-        ]
+            (0..max_ordinal).prop_map(Specifier::Only),
+            (0..max_ordinal, 0..max_ordinal)
+                .prop_map(|(a, b)| Specifier::Range(a.min(b), a.max(b))),
+        ];
+        leaf.prop_recursive(2, 4, 3, move |inner| {
+            prop_oneof![
+                collection::vec(inner.clone(), 1..4).prop_map(Specifier::List),
+                (inner, 1..max_ordinal).prop_map(|(base, step)| Specifier::Step {
+                    base: Box::new(base),
+                    step,
+                }),
+            ]
+        })
         .boxed()
     }
 
-    fn specification_strategy() -> BoxedStrategy<Specification> {
-        (specifier_strategy(60), specifier_strategy(24))
-            .prop_map(|(minute, hour)| Specification { minute, hour })
-            .boxed()
-    }
-
-    fn time_strategy() -> BoxedStrategy<NaiveTime> {
-        (0..24u32, 0..60u32)
-            .prop_map(|(hour, minute)| NaiveTime::from_hms(hour, minute, 0))
+    fn datetime_strategy() -> BoxedStrategy<NaiveDateTime> {
+        // Stick to days that exist in every month so the start time is always
+        // valid regardless of the generated month.
+        (2000..2100i32, 1..=12u32, 1..=28u32, 0..24u32, 0..60u32)
+            .prop_map(|(year, month, day, hour, minute)| {
+                NaiveDate::from_ymd(year, month, day).and_hms(hour, minute, 0)
+            })
             .boxed()
     }
 
-    // Lets pick a random spec and start time, and get the next time.
-    // Then check the following invariants:
+    // Pick a random time-of-day spec and start time, then get the next time.
+    // The date fields are left as `Any` so a match is always reachable within
+    // two days. Then check the following invariants:
     // - The returned time actually matches the pattern
-    // - There are no earlier matches
+    // - There are no earlier matches between the start and the returned time
     proptest! {
         #[test]
         fn test_no_earlier_matches(
-            specification in specification_strategy(),
-            current_time in time_strategy()
+            minute in specifier_strategy(60),
+            hour in specifier_strategy(24),
+            current_time in datetime_strategy(),
         ) {
-            let (next_time, day) = get_next_time(&specification, &current_time);
+            let specification = Specification::new(
+                minute,
+                hour,
+                Specifier::Any,
+                Specifier::Any,
+                Specifier::Any,
+            );
+            let start = Utc.from_utc_datetime(&current_time);
+            let next_time = get_next_time(&specification, &start).unwrap().naive_utc();
             // Check our return value actually matches
             prop_assert!(
                 specification.matches(&next_time),
                 "Next time {} doesn't match specification.",
                 next_time,
             );
-            // Check for earlier values
-            let mut check_time = next_time.clone();
-            let mut check_day = day.clone();
-            loop {
-                if (&check_time, &check_day) == (&current_time, &Day::Today) {
-                    // we reached our starting time without incident
-                    break;
-                }
-
-                // Move back one step
-                if check_time == NaiveTime::from_hms(0, 0, 0) && check_day == Day::Tomorrow {
-                    check_day = Day::Today;
-                };
-                check_time = check_time - Duration::minutes(1);
-
-                // Check if we have a new match
+            // Walk forward minute by minute from the start, asserting nothing
+            // earlier than the returned time matches.
+            let mut check_time = current_time
+                .with_second(0)
+                .and_then(|t| t.with_nanosecond(0))
+                .unwrap();
+            while check_time < next_time {
                 prop_assert!(
                     !specification.matches(&check_time),
-                    "Said next time was {:?}, but found earlier match {:?}.",
-                    (&next_time, &day),
-                    (&check_time, &check_day)
+                    "Said next time was {}, but found earlier match {}.",
+                    next_time,
+                    check_time,
                 );
+                check_time += Duration::minutes(1);
             }
         }
     }
 
+    #[test]
+    fn test_specifier_matches_ordinal() {
+        // Range is inclusive at both ends.
+        let range = Specifier::from_str_max("1-5", 60).unwrap();
+        assert!(!range.matches_ordinal(0));
+        assert!(range.matches_ordinal(1));
+        assert!(range.matches_ordinal(5));
+        assert!(!range.matches_ordinal(6));
+
+        // Lists match any of their members.
+        let list = Specifier::from_str_max("0,15,30", 60).unwrap();
+        assert!(list.matches_ordinal(15));
+        assert!(!list.matches_ordinal(16));
+
+        // Step over a wildcard counts from zero.
+        let step = Specifier::from_str_max("*/5", 60).unwrap();
+        assert!(step.matches_ordinal(0));
+        assert!(step.matches_ordinal(10));
+        assert!(!step.matches_ordinal(7));
+
+        // Step over a range counts from the range start.
+        let ranged_step = Specifier::from_str_max("10-50/10", 60).unwrap();
+        assert!(ranged_step.matches_ordinal(10));
+        assert!(ranged_step.matches_ordinal(40));
+        assert!(!ranged_step.matches_ordinal(15));
+        assert!(!ranged_step.matches_ordinal(60));
+    }
+
+    /// Build an all-`Any` specification with only the minute and hour pinned,
+    /// for exercising the time-of-day logic.
+    fn time_spec(minute: Specifier, hour: Specifier) -> Specification {
+        Specification::new(minute, hour, Specifier::Any, Specifier::Any, Specifier::Any)
+    }
+
     #[test]
     fn test_spec_any_minute_specific_hour() {
+        let start = dt(2020, 1, 1, 12, 00);
         assert_eq!(
-            get_next_time(
-                &Specification::new(Specifier::Any, Specifier::Only(12)),
-                &NaiveTime::from_hms(12, 00, 0),
-            ),
-            (NaiveTime::from_hms(12, 00, 0), Day::Today)
+            get_next_time(&time_spec(Specifier::Any, Specifier::Only(12)), &start).unwrap(),
+            dt(2020, 1, 1, 12, 00)
         );
         assert_eq!(
-            get_next_time(
-                &Specification::new(Specifier::Any, Specifier::Only(15)),
-                &NaiveTime::from_hms(12, 00, 0)
-            ),
-            (NaiveTime::from_hms(15, 00, 0), Day::Today)
+            get_next_time(&time_spec(Specifier::Any, Specifier::Only(15)), &start).unwrap(),
+            dt(2020, 1, 1, 15, 00)
         );
+        // An hour earlier today means we roll over to tomorrow.
         assert_eq!(
-            get_next_time(
-                &Specification::new(Specifier::Any, Specifier::Only(9)),
-                &NaiveTime::from_hms(12, 00, 0)
-            ),
-            (NaiveTime::from_hms(09, 00, 0), Day::Tomorrow)
+            get_next_time(&time_spec(Specifier::Any, Specifier::Only(9)), &start).unwrap(),
+            dt(2020, 1, 2, 09, 00)
         );
     }
 
     #[test]
     fn test_spec_specific_minute_any_hour() {
+        let start = dt(2020, 1, 1, 12, 00);
         assert_eq!(
-            get_next_time(
-                &Specification::new(Specifier::Only(0), Specifier::Any),
-                &NaiveTime::from_hms(12, 00, 0)
-            ),
-            (NaiveTime::from_hms(12, 00, 0), Day::Today)
+            get_next_time(&time_spec(Specifier::Only(0), Specifier::Any), &start).unwrap(),
+            dt(2020, 1, 1, 12, 00)
         );
         assert_eq!(
-            get_next_time(
-                &Specification::new(Specifier::Only(7), Specifier::Any),
-                &NaiveTime::from_hms(12, 00, 0)
-            ),
-            (NaiveTime::from_hms(12, 07, 0), Day::Today)
+            get_next_time(&time_spec(Specifier::Only(7), Specifier::Any), &start).unwrap(),
+            dt(2020, 1, 1, 12, 07)
         );
+        // Past the last minute of the day, the next hit is after midnight.
+        let late = dt(2020, 1, 1, 23, 57);
         assert_eq!(
-            get_next_time(
-                &Specification::new(Specifier::Only(7), Specifier::Any),
-                &NaiveTime::from_hms(23, 57, 0)
-            ),
-            (NaiveTime::from_hms(00, 07, 0), Day::Tomorrow)
+            get_next_time(&time_spec(Specifier::Only(7), Specifier::Any), &late).unwrap(),
+            dt(2020, 1, 2, 00, 07)
         );
     }
 
     #[test]
     fn test_spec_specific_minute_specific_hour() {
+        let start = dt(2020, 1, 1, 12, 00);
         assert_eq!(
-            get_next_time(
-                &Specification::new(Specifier::Only(0), Specifier::Only(12)),
-                &NaiveTime::from_hms(12, 00, 0)
-            ),
-            (NaiveTime::from_hms(12, 00, 0), Day::Today)
+            get_next_time(&time_spec(Specifier::Only(0), Specifier::Only(12)), &start).unwrap(),
+            dt(2020, 1, 1, 12, 00)
         );
         assert_eq!(
-            get_next_time(
-                &Specification::new(Specifier::Only(13), Specifier::Only(13)),
-                &NaiveTime::from_hms(12, 00, 0)
-            ),
-            (NaiveTime::from_hms(13, 13, 0), Day::Today)
+            get_next_time(&time_spec(Specifier::Only(13), Specifier::Only(13)), &start).unwrap(),
+            dt(2020, 1, 1, 13, 13)
         );
         assert_eq!(
-            get_next_time(
-                &Specification::new(Specifier::Only(11), Specifier::Only(11)),
-                &NaiveTime::from_hms(12, 00, 0)
-            ),
-            (NaiveTime::from_hms(11, 11, 0), Day::Tomorrow)
+            get_next_time(&time_spec(Specifier::Only(11), Specifier::Only(11)), &start).unwrap(),
+            dt(2020, 1, 2, 11, 11)
         );
     }
 
     #[test]
-    fn test_spec_any_minute_any_hour() {
+    fn test_spec_date_fields() {
+        // 30 1 15 6 * -> 01:30 on the 15th of June.
+        let spec = Specification::new(
+            Specifier::Only(30),
+            Specifier::Only(1),
+            Specifier::Only(15),
+            Specifier::Only(6),
+            Specifier::Any,
+        );
         assert_eq!(
-            get_next_time(
-                &Specification::new(Specifier::Any, Specifier::Any),
-                &NaiveTime::from_hms(00, 00, 0)
-            ),
-            (NaiveTime::from_hms(00, 00, 0), Day::Today)
+            get_next_time(&spec, &dt(2020, 1, 1, 0, 0)).unwrap(),
+            dt(2020, 6, 15, 1, 30)
         );
+    }
+
+    #[test]
+    fn test_dom_dow_or_semantics() {
+        // The 1st of the month OR any Monday, whichever comes first.
+        let spec = Specification::new(
+            Specifier::Only(0),
+            Specifier::Only(0),
+            Specifier::Only(1),
+            Specifier::Any,
+            Specifier::Only(1),
+        );
+        // Starting Thursday 2020-01-02, the next hit is Monday the 6th (via the
+        // day-of-week branch), well before the 1st of February.
         assert_eq!(
-            get_next_time(
-                &Specification::new(Specifier::Any, Specifier::Any),
-                &NaiveTime::from_hms(23, 59, 0)
-            ),
-            (NaiveTime::from_hms(23, 59, 0), Day::Today)
+            get_next_time(&spec, &dt(2020, 1, 2, 0, 0)).unwrap(),
+            dt(2020, 1, 6, 0, 0)
+        );
+    }
+
+    #[test]
+    fn test_occurrences_iterator() {
+        // Daily at 00:00: the next three runs are three consecutive midnights.
+        let spec = Specification::new(
+            Specifier::Only(0),
+            Specifier::Only(0),
+            Specifier::Any,
+            Specifier::Any,
+            Specifier::Any,
         );
+        let start = dt(2020, 1, 1, 12, 0);
+        let runs: Vec<_> = spec.iter_from(start).take(3).collect();
+        assert_eq!(
+            runs,
+            vec![
+                dt(2020, 1, 2, 0, 0),
+                dt(2020, 1, 3, 0, 0),
+                dt(2020, 1, 4, 0, 0),
+            ]
+        );
+
+        // `take_while` bounds the iteration to a window.
+        let end = dt(2020, 1, 3, 0, 0);
+        let within: Vec<_> = spec.iter_from(start).take_while(|t| t < &end).collect();
+        assert_eq!(within, vec![dt(2020, 1, 2, 0, 0)]);
+    }
+
+    #[test]
+    fn test_unsatisfiable_spec_errors() {
+        // The 30th of February never occurs.
+        let spec = Specification::new(
+            Specifier::Only(0),
+            Specifier::Only(0),
+            Specifier::Only(30),
+            Specifier::Only(2),
+            Specifier::Any,
+        );
+        assert!(get_next_time(&spec, &dt(2020, 1, 1, 0, 0)).is_err());
+    }
+
+    #[test]
+    fn test_dst_spring_forward_rolls_to_next_instant() {
+        use chrono_tz::Europe::London;
+        // On 2021-03-28 the UK clocks jump from 01:00 to 02:00, so 01:30 never
+        // occurs. A job for 01:30 should roll forward to the first valid instant.
+        let spec = time_spec(Specifier::Only(30), Specifier::Only(1));
+        let start = London
+            .from_local_datetime(&NaiveDate::from_ymd(2021, 3, 28).and_hms(0, 0, 0))
+            .unwrap();
+        let next = get_next_time(&spec, &start).unwrap();
+        // The wall clock skips straight to 02:00.
+        assert_eq!(next.naive_local(), NaiveDate::from_ymd(2021, 3, 28).and_hms(2, 0, 0));
+    }
+
+    #[test]
+    fn test_dst_autumn_back_picks_first_occurrence() {
+        use chrono_tz::Europe::London;
+        // On 2021-10-31 the UK clocks fall back from 02:00 to 01:00, so 01:30
+        // happens twice. We should pick the first (summer-time) occurrence.
+        let spec = time_spec(Specifier::Only(30), Specifier::Only(1));
+        let start = London
+            .from_local_datetime(&NaiveDate::from_ymd(2021, 10, 31).and_hms(0, 0, 0))
+            .unwrap();
+        let next = get_next_time(&spec, &start).unwrap();
+        let expected = match London
+            .from_local_datetime(&NaiveDate::from_ymd(2021, 10, 31).and_hms(1, 30, 0))
+        {
+            LocalResult::Ambiguous(earliest, _) => earliest,
+            other => panic!("expected an ambiguous local time, got {:?}", other),
+        };
+        assert_eq!(next, expected);
     }
 }